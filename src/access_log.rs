@@ -0,0 +1,309 @@
+use std::{
+    fmt::Write as _,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    http::header::{REFERER, USER_AGENT},
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use serde::Serialize;
+use time::{macros::format_description, OffsetDateTime};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
+};
+use tracing::{error, info};
+
+use crate::{auth::TokenName, config::AccessLogFormat, crate_name::CrateName, AppState};
+
+/// Request log in a format suitable for log analysis tools, kept separate from the tracing output.
+pub struct AccessLog {
+    format: AccessLogFormat,
+    writer: Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+}
+
+impl AccessLog {
+    pub async fn new(config: &crate::config::AccessLog) -> Result<Self, io::Error> {
+        let writer: Box<dyn AsyncWrite + Send + Unpin> = if let Some(path) = &config.path {
+            info!("Writing access log to {}", path.display());
+
+            Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?,
+            )
+        } else {
+            info!("Writing access log to stdout");
+
+            Box::new(tokio::io::stdout())
+        };
+
+        Ok(Self {
+            format: config.format,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    async fn write(&self, entry: &AccessLogEntry) -> Result<(), io::Error> {
+        let mut line = entry.format(self.format)?;
+        line.push('\n');
+
+        let mut writer = self.writer.lock().await;
+        writer.write_all(line.as_bytes()).await?;
+        writer.flush().await
+    }
+}
+
+#[derive(Serialize)]
+struct AccessLogEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    timestamp: OffsetDateTime,
+    remote_addr: IpAddr,
+    method: String,
+    uri: String,
+    version: String,
+    status: u16,
+    bytes: u64,
+    duration_ms: u128,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    token_name: Option<String>,
+    crate_name: Option<CrateName>,
+}
+
+impl AccessLogEntry {
+    fn format(&self, format: AccessLogFormat) -> Result<String, io::Error> {
+        if format == AccessLogFormat::Json {
+            return serde_json::to_string(self).map_err(io::Error::from);
+        }
+
+        let timestamp = self
+            .timestamp
+            .format(format_description!(
+                "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+            ))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut line = format!(
+            "{} - {} [{}] \"{} {} {}\" {} {}",
+            self.remote_addr,
+            self.token_name.as_deref().unwrap_or("-"),
+            timestamp,
+            self.method,
+            escape(&self.uri),
+            self.version,
+            self.status,
+            self.bytes,
+        );
+
+        if format == AccessLogFormat::Combined {
+            // Writing to a String can't fail
+            let _ = write!(
+                line,
+                " \"{}\" \"{}\" \"{}\"",
+                escape(self.referer.as_deref().unwrap_or("-")),
+                escape(self.user_agent.as_deref().unwrap_or("-")),
+                self.crate_name
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| String::from("-")),
+            );
+        }
+
+        Ok(line)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Middleware which writes an access log entry for every request, if the access log is enabled.
+///
+/// Handlers can enrich the entry with the identity the request was authorized as and the crate it
+/// was about by inserting a `TokenName` and a `CrateName` into the response extensions.
+///
+/// The entry is only written once the response body has been fully sent (or dropped), so that the
+/// size of streamed bodies like crate downloads can be counted.
+pub async fn middleware(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.access_log.is_none() {
+        return next.run(request).await;
+    }
+
+    let start = Instant::now();
+    let timestamp = OffsetDateTime::now_utc();
+
+    // Scoped so that nothing borrowing the request is held across the await below
+    let (referer, user_agent) = {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        (header(REFERER), header(USER_AGENT))
+    };
+
+    let method = request.method().to_string();
+    let uri = request.uri().to_string();
+    let version = format!("{:?}", request.version());
+
+    let response = next.run(request).await;
+
+    let entry = AccessLogEntry {
+        timestamp,
+        remote_addr: remote_addr.ip(),
+        method,
+        uri,
+        version,
+        status: response.status().as_u16(),
+        bytes: 0,
+        duration_ms: 0,
+        referer,
+        user_agent,
+        token_name: response
+            .extensions()
+            .get::<TokenName>()
+            .map(|token_name| token_name.0.clone()),
+        crate_name: response.extensions().get::<CrateName>().cloned(),
+    };
+
+    response.map(|body| {
+        Body::new(CountingBody {
+            inner: body,
+            state,
+            start,
+            entry: Some(entry),
+        })
+    })
+}
+
+/// Response body wrapper which counts the bytes sent, and writes the access log entry when dropped
+struct CountingBody {
+    inner: Body,
+    state: Arc<AppState>,
+    start: Instant,
+    entry: Option<AccessLogEntry>,
+}
+
+impl HttpBody for CountingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let (Some(data), Some(entry)) = (frame.data_ref(), &mut this.entry) {
+                entry.bytes += data.len() as u64;
+            }
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        let Some(mut entry) = self.entry.take() else {
+            return;
+        };
+
+        entry.duration_ms = self.start.elapsed().as_millis();
+
+        let state = self.state.clone();
+
+        tokio::spawn(async move {
+            if let Some(access_log) = &state.access_log {
+                if let Err(e) = access_log.write(&entry).await {
+                    error!("Failed to write access log entry: {e}");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            timestamp: datetime!(2023-12-01 13:55:36 UTC),
+            remote_addr: IpAddr::V4(Ipv4Addr::new(10, 1, 1, 1)),
+            method: String::from("GET"),
+            uri: String::from("/crates/foo/1.0.0/download"),
+            version: String::from("HTTP/1.1"),
+            status: 200,
+            bytes: 1234,
+            duration_ms: 5,
+            referer: None,
+            user_agent: Some(String::from("cargo \"1.74.0\"")),
+            token_name: Some(String::from("ci")),
+            crate_name: Some(CrateName::new("foo").unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_common_format() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Common).unwrap(),
+            "10.1.1.1 - ci [01/Dec/2023:13:55:36 +0000] \"GET /crates/foo/1.0.0/download HTTP/1.1\" 200 1234"
+        );
+    }
+
+    #[test]
+    fn test_combined_format() {
+        assert_eq!(
+            entry().format(AccessLogFormat::Combined).unwrap(),
+            "10.1.1.1 - ci [01/Dec/2023:13:55:36 +0000] \"GET /crates/foo/1.0.0/download HTTP/1.1\" 200 1234 \"-\" \"cargo \\\"1.74.0\\\"\" \"foo\""
+        );
+    }
+
+    #[test]
+    fn test_json_format() {
+        let json: serde_json::Value =
+            serde_json::from_str(&entry().format(AccessLogFormat::Json).unwrap()).unwrap();
+
+        assert_eq!(json["timestamp"], "2023-12-01T13:55:36Z");
+        assert_eq!(json["token_name"], "ci");
+        assert_eq!(json["crate_name"], "foo");
+        assert_eq!(json["bytes"], 1234);
+    }
+}
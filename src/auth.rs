@@ -5,17 +5,36 @@ use axum::{
 };
 use tracing::{info, warn};
 
-use crate::error::ErrorResponse;
+use crate::{crate_name::CrateName, error::ErrorResponse};
 
+pub mod asymmetric;
 pub mod token;
 
 pub enum Auth {
     None,
     Token(token::Token),
+    Asymmetric(asymmetric::Asymmetric),
+}
+
+/// An operation which modifies the registry, for auth methods which grant tokens per operation
+pub enum Mutation<'a> {
+    Publish {
+        name: &'a CrateName,
+        vers: &'a semver::Version,
+        cksum: &'a str,
+    },
+    Yank {
+        name: &'a CrateName,
+        vers: &'a semver::Version,
+    },
+    Unyank {
+        name: &'a CrateName,
+        vers: &'a semver::Version,
+    },
 }
 
 impl Auth {
-    pub async fn new(config: &crate::config::Auth) -> Result<Self, Error> {
+    pub async fn new(config: &crate::config::Auth, root_url: &str) -> Result<Self, Error> {
         match config {
             crate::config::Auth::None => {
                 warn!("Disabling authentication!");
@@ -29,25 +48,77 @@ impl Auth {
 
                 Ok(Self::Token(token::Token::new(token)))
             }
+
+            crate::config::Auth::Asymmetric(asymmetric) => {
+                info!("Using asymmetric token authentication");
+
+                Ok(Self::Asymmetric(asymmetric::Asymmetric::new(
+                    asymmetric, root_url,
+                )))
+            }
         }
     }
 
     pub fn auth_required(&self) -> bool {
         match self {
             Self::None => false,
-            Self::Token(_) => true,
+            Self::Token(_) | Self::Asymmetric(_) => true,
         }
     }
 
+    /// Check that the token is valid, without checking what operations it grants access to.
+    /// Returns the name of the identity the token belongs to, if any.
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Option<TokenName>, Error> {
+        self.authorize_with(token, |asymmetric| asymmetric.authenticate(token))
+    }
+
     // TODO: Implement more granular authorization
-    pub fn authorize(&self, token: Option<&str>) -> Result<(), Error> {
+    /// Returns the name of the identity the token belongs to, if any.
+    pub fn authorize(&self, token: Option<&str>) -> Result<Option<TokenName>, Error> {
+        self.authorize_with(token, |asymmetric| asymmetric.authorize(token))
+    }
+
+    pub fn authorize_mutation(
+        &self,
+        token: Option<&str>,
+        mutation: &Mutation,
+    ) -> Result<Option<TokenName>, Error> {
+        self.authorize_with(token, |asymmetric| {
+            asymmetric.authorize_mutation(token, mutation)
+        })
+    }
+
+    /// Tokens for the other auth methods grant access to every operation, so only asymmetric
+    /// tokens need a check specific to the operation.
+    fn authorize_with<'a>(
+        &'a self,
+        token: Option<&str>,
+        asymmetric_check: impl FnOnce(&'a asymmetric::Asymmetric) -> Result<&'a str, Error>,
+    ) -> Result<Option<TokenName>, Error> {
         match self {
-            Self::None => Ok(()),
-            Self::Token(token_auth) => token_auth.authorize(token),
+            Self::None => Ok(None),
+            Self::Token(token_auth) => token_auth
+                .authorize(token)
+                .map(|()| Some(TokenName::new(token_auth.name()))),
+            Self::Asymmetric(asymmetric) => {
+                asymmetric_check(asymmetric).map(|name| Some(TokenName::new(name)))
+            }
         }
     }
 }
 
+/// The name of the identity a request was authorized as.
+///
+/// Handlers insert it into the response extensions so that it can be recorded in the access log.
+#[derive(Clone, Debug)]
+pub struct TokenName(pub String);
+
+impl TokenName {
+    fn new(name: &str) -> Self {
+        Self(name.to_owned())
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("The provided token is invalid")]
@@ -0,0 +1,357 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use pasetors::{
+    keys::AsymmetricPublicKey,
+    paserk::{FormatAsPaserk, Id},
+    token::UntrustedToken,
+    version3::{PublicToken, V3},
+    Public,
+};
+use serde::Deserialize;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+use tracing::{debug, info};
+
+use crate::{
+    auth::{Error, Mutation},
+    crate_name::CrateName,
+};
+
+/// Authentication with Cargo's asymmetric tokens, as specified by
+/// <https://rust-lang.github.io/rfcs/3231-cargo-asymmetric-tokens.html>
+///
+/// Cargo signs a fresh PASETO `v3.public` token for every request with the user's secret key,
+/// so only the public keys need to be configured.
+pub struct Asymmetric {
+    /// Keys indexed by their PASERK ID, which Cargo sends in the `kip` field of the token footer
+    keys: HashMap<String, Key>,
+    index_url: String,
+    max_token_age: Duration,
+}
+
+struct Key {
+    name: String,
+    public_key: AsymmetricPublicKey<V3>,
+    subject: Option<String>,
+}
+
+impl Asymmetric {
+    pub fn new(config: &crate::config::AsymmetricAuth, root_url: &str) -> Self {
+        let mut keys = HashMap::new();
+
+        for key in &config.keys {
+            let mut kip = String::new();
+            // Writing to a String can't fail
+            let _ = FormatAsPaserk::fmt(&Id::from(&key.public_key), &mut kip);
+
+            info!("Registered public key {kip} for {}", key.name);
+
+            keys.insert(
+                kip,
+                Key {
+                    name: key.name.clone(),
+                    public_key: key.public_key.clone(),
+                    subject: key.subject.clone(),
+                },
+            );
+        }
+
+        Self {
+            keys,
+            index_url: format!("sparse+{}/index/", root_url.trim_end_matches('/')),
+            max_token_age: Duration::seconds(
+                i64::try_from(config.max_token_age_secs).unwrap_or(i64::MAX),
+            ),
+        }
+    }
+
+    /// Check that the token is valid, regardless of the operation it was issued for,
+    /// and return the name of its key.
+    pub fn authenticate(&self, token: Option<&str>) -> Result<&str, Error> {
+        let (key, _) = self.verify(token)?;
+        Ok(&key.name)
+    }
+
+    /// Check that the token is valid for a read operation, and return the name of its key.
+    pub fn authorize(&self, token: Option<&str>) -> Result<&str, Error> {
+        let (key, message) = self.verify(token)?;
+
+        if message.mutation.is_some()
+            || message.name.is_some()
+            || message.vers.is_some()
+            || message.cksum.is_some()
+        {
+            debug!("Token for a mutation used for a read operation");
+            return Err(Error::Forbidden);
+        }
+
+        Ok(&key.name)
+    }
+
+    /// Check that the token is valid for the given mutation, and return the name of its key.
+    pub fn authorize_mutation(
+        &self,
+        token: Option<&str>,
+        mutation: &Mutation,
+    ) -> Result<&str, Error> {
+        let (key, message) = self.verify(token)?;
+
+        let (operation, name, vers, cksum) = match mutation {
+            Mutation::Publish { name, vers, cksum } => ("publish", name, vers, Some(cksum)),
+            Mutation::Yank { name, vers } => ("yank", name, vers, None),
+            Mutation::Unyank { name, vers } => ("unyank", name, vers, None),
+        };
+
+        if message.mutation.as_deref() != Some(operation) {
+            debug!("Token was not issued for a {operation} operation");
+            return Err(Error::Forbidden);
+        }
+
+        let name_matches = message
+            .name
+            .as_deref()
+            .and_then(|n| CrateName::new(n).ok())
+            .is_some_and(|n| &n == *name);
+
+        // Build metadata is ignored, like when publishing
+        let vers_matches = message
+            .vers
+            .as_deref()
+            .and_then(|v| semver::Version::parse(v).ok())
+            .is_some_and(|v| v.cmp_precedence(vers) == Ordering::Equal);
+
+        if !name_matches || !vers_matches {
+            debug!("Token was issued for a different crate or version");
+            return Err(Error::Forbidden);
+        }
+
+        if let Some(cksum) = cksum {
+            if !message
+                .cksum
+                .as_deref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(cksum))
+            {
+                debug!("Token was issued for a different checksum");
+                return Err(Error::Forbidden);
+            }
+        }
+
+        Ok(&key.name)
+    }
+
+    fn verify(&self, token: Option<&str>) -> Result<(&Key, Message), Error> {
+        let token = token.ok_or(Error::Unauthorized)?;
+
+        let untrusted_token = UntrustedToken::<Public, V3>::try_from(token).map_err(|_| {
+            debug!("Malformed asymmetric token");
+            Error::Forbidden
+        })?;
+
+        // The footer can only be trusted after verifying the token, but it's needed to find
+        // which key to verify the token with
+        let footer: Footer =
+            serde_json::from_slice(untrusted_token.untrusted_footer()).map_err(|_| {
+                debug!("Malformed asymmetric token footer");
+                Error::Forbidden
+            })?;
+
+        let key = self.keys.get(&footer.kip).ok_or_else(|| {
+            debug!("Unknown key ID {}", footer.kip);
+            Error::Forbidden
+        })?;
+
+        let trusted_token = PublicToken::verify(&key.public_key, &untrusted_token, None, None)
+            .map_err(|_| {
+                debug!("Invalid asymmetric token signature");
+                Error::Forbidden
+            })?;
+
+        if !urls_match(&footer.url, &self.index_url) {
+            debug!("Token was issued for a different registry: {}", footer.url);
+            return Err(Error::Forbidden);
+        }
+
+        let message: Message = serde_json::from_str(trusted_token.payload()).map_err(|_| {
+            debug!("Malformed asymmetric token message");
+            Error::Forbidden
+        })?;
+
+        if message.v.is_some_and(|v| v != 1) {
+            debug!("Unsupported asymmetric token version");
+            return Err(Error::Forbidden);
+        }
+
+        let iat = OffsetDateTime::parse(&message.iat, &Rfc3339).map_err(|_| {
+            debug!("Malformed asymmetric token issue time");
+            Error::Forbidden
+        })?;
+
+        // Allow for some clock skew in both directions
+        if (OffsetDateTime::now_utc() - iat).abs() > self.max_token_age {
+            debug!("Asymmetric token has expired");
+            return Err(Error::Forbidden);
+        }
+
+        if let Some(subject) = &key.subject {
+            if message.sub.as_ref() != Some(subject) {
+                debug!("Token was issued for a different subject");
+                return Err(Error::Forbidden);
+            }
+        }
+
+        Ok((key, message))
+    }
+}
+
+fn urls_match(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+#[derive(Deserialize)]
+struct Footer {
+    url: String,
+    kip: String,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    iat: String,
+    sub: Option<String>,
+    mutation: Option<String>,
+    name: Option<String>,
+    vers: Option<String>,
+    cksum: Option<String>,
+    v: Option<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use pasetors::keys::{AsymmetricKeyPair, Generate};
+    use serde_json::json;
+
+    use super::*;
+    use crate::config::{AsymmetricAuth, AsymmetricKey};
+
+    const ROOT_URL: &str = "https://foo.bar";
+
+    fn setup() -> (Asymmetric, AsymmetricKeyPair<V3>, String) {
+        let key_pair = AsymmetricKeyPair::<V3>::generate().unwrap();
+
+        let mut kip = String::new();
+        FormatAsPaserk::fmt(&Id::from(&key_pair.public), &mut kip).unwrap();
+
+        let auth = Asymmetric::new(
+            &AsymmetricAuth {
+                keys: vec![AsymmetricKey {
+                    name: String::from("ci"),
+                    public_key: key_pair.public.clone(),
+                    subject: None,
+                }],
+                max_token_age_secs: 300,
+            },
+            ROOT_URL,
+        );
+
+        (auth, key_pair, kip)
+    }
+
+    /// Sign a token the same way Cargo does
+    fn sign(
+        key_pair: &AsymmetricKeyPair<V3>,
+        kip: &str,
+        url: &str,
+        message: serde_json::Value,
+    ) -> String {
+        let mut message = message;
+        message["iat"] = json!(OffsetDateTime::now_utc().format(&Rfc3339).unwrap());
+
+        PublicToken::sign(
+            &key_pair.secret,
+            message.to_string().as_bytes(),
+            Some(json!({ "url": url, "kip": kip }).to_string().as_bytes()),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_token() {
+        let (auth, key_pair, kip) = setup();
+        let token = sign(&key_pair, &kip, "sparse+https://foo.bar/index/", json!({}));
+
+        assert_eq!(auth.authenticate(Some(&token)).unwrap(), "ci");
+        assert_eq!(auth.authorize(Some(&token)).unwrap(), "ci");
+    }
+
+    #[test]
+    fn test_wrong_registry() {
+        let (auth, key_pair, kip) = setup();
+        let token = sign(
+            &key_pair,
+            &kip,
+            "sparse+https://other.registry/index/",
+            json!({}),
+        );
+
+        assert!(matches!(
+            auth.authorize(Some(&token)),
+            Err(Error::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn test_missing_token() {
+        let (auth, _, _) = setup();
+
+        assert!(matches!(auth.authorize(None), Err(Error::Unauthorized)));
+    }
+
+    #[test]
+    fn test_publish_token() {
+        let (auth, key_pair, kip) = setup();
+        let token = sign(
+            &key_pair,
+            &kip,
+            "sparse+https://foo.bar/index/",
+            json!({ "mutation": "publish", "name": "foo", "vers": "1.0.0", "cksum": "abcd" }),
+        );
+
+        let name = CrateName::new("foo").unwrap();
+        let vers = semver::Version::new(1, 0, 0);
+
+        auth.authorize_mutation(
+            Some(&token),
+            &Mutation::Publish {
+                name: &name,
+                vers: &vers,
+                cksum: "abcd",
+            },
+        )
+        .unwrap();
+
+        assert!(matches!(
+            auth.authorize_mutation(
+                Some(&token),
+                &Mutation::Publish {
+                    name: &name,
+                    vers: &vers,
+                    cksum: "ef01",
+                },
+            ),
+            Err(Error::Forbidden)
+        ));
+        assert!(matches!(
+            auth.authorize_mutation(
+                Some(&token),
+                &Mutation::Yank {
+                    name: &name,
+                    vers: &vers,
+                },
+            ),
+            Err(Error::Forbidden)
+        ));
+        assert!(matches!(
+            auth.authorize(Some(&token)),
+            Err(Error::Forbidden)
+        ));
+    }
+}
@@ -7,15 +7,21 @@ use crate::auth::Error;
 
 pub struct Token {
     token_hash: [u8; 64],
+    name: String,
 }
 
 impl Token {
     pub fn new(config: &crate::config::TokenAuth) -> Self {
         Self {
             token_hash: config.token_hash,
+            name: config.name.clone(),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn authorize(&self, token: Option<&str>) -> Result<(), Error> {
         let token = token.ok_or(Error::Unauthorized)?;
         let token_hash = Sha512::digest(token);
@@ -34,6 +40,7 @@ impl Debug for Token {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("Token")
             .field("token_hash", &"<REDACTED>")
+            .field("name", &self.name)
             .finish()
     }
 }
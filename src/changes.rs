@@ -0,0 +1,218 @@
+use std::{
+    borrow::Cow,
+    str::{self, FromStr},
+};
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::crate_name::CrateName;
+
+/// Number of events returned by a changes feed request if no limit is given
+pub const DEFAULT_LIMIT: usize = 100;
+/// Maximum number of events returned by a single changes feed request
+pub const MAX_LIMIT: usize = 1000;
+
+/// Persisted, ordered log of publish and yank events, stored as one JSON object per line.
+///
+/// NOTE: The whole log is read and rewritten on every publish, yank and unyank, so mutations get
+/// slower as the log grows. This is fine for the number of crates private registries usually have.
+#[derive(Default)]
+pub struct ChangeLog {
+    pub events: Vec<ChangeEvent>,
+}
+
+impl ChangeLog {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ChangeLogError> {
+        Ok(Self {
+            events: str::from_utf8(bytes)?
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| serde_json::from_str(l.trim_end()))
+                .collect::<Result<_, serde_json::Error>>()?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ChangeLogError> {
+        let mut bytes = Vec::new();
+
+        for event in &self.events {
+            serde_json::to_writer(&mut bytes, event)?;
+            bytes.push(b'\n');
+        }
+
+        Ok(bytes)
+    }
+
+    pub fn push(&mut self, action: ChangeAction, name: CrateName, vers: semver::Version) {
+        let seq = self.events.last().map_or(1, |event| event.seq + 1);
+
+        self.events.push(ChangeEvent {
+            seq,
+            timestamp: OffsetDateTime::now_utc(),
+            action,
+            name,
+            vers,
+        });
+    }
+
+    /// The action of the latest event recorded for a crate version, if any
+    pub fn latest_action(&self, name: &CrateName, vers: &semver::Version) -> Option<ChangeAction> {
+        self.events
+            .iter()
+            .rev()
+            .find(|event| &event.name == name && &event.vers == vers)
+            .map(|event| event.action)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChangeLogError {
+    #[error("Invalid UTF-8")]
+    Utf8(#[from] str::Utf8Error),
+    #[error("JSON error")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Monotonically increasing sequence number of the event, starting from 1.
+    pub seq: u64,
+    /// When the event happened.
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub action: ChangeAction,
+    /// The name of the crate.
+    pub name: CrateName,
+    /// The version of the crate.
+    pub vers: semver::Version,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Publish,
+    Yank,
+    Unyank,
+}
+
+/// Starting point of a changes feed request, either a sequence number or a RFC 3339 timestamp.
+/// Only events strictly after it are returned.
+#[derive(Debug)]
+pub enum ChangesSince {
+    Seq(u64),
+    Timestamp(OffsetDateTime),
+}
+
+impl ChangesSince {
+    pub fn includes(&self, event: &ChangeEvent) -> bool {
+        match self {
+            Self::Seq(seq) => event.seq > *seq,
+            Self::Timestamp(timestamp) => event.timestamp > *timestamp,
+        }
+    }
+}
+
+impl Default for ChangesSince {
+    fn default() -> Self {
+        Self::Seq(0)
+    }
+}
+
+impl FromStr for ChangesSince {
+    type Err = time::error::Parse;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(seq) = s.parse() {
+            return Ok(Self::Seq(seq));
+        }
+
+        // An unencoded `+` in a timezone offset is decoded as a space in query strings
+        Ok(Self::Timestamp(OffsetDateTime::parse(
+            &s.replace(' ', "+"),
+            &Rfc3339,
+        )?))
+    }
+}
+
+impl<'de> Deserialize<'de> for ChangesSince {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: Cow<'de, str> = Deserialize::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn test_changes_since_parse() {
+        assert!(matches!(
+            ChangesSince::from_str("42").unwrap(),
+            ChangesSince::Seq(42)
+        ));
+        assert!(matches!(
+            ChangesSince::from_str("2023-12-01T13:55:36Z").unwrap(),
+            ChangesSince::Timestamp(t) if t == datetime!(2023-12-01 13:55:36 UTC)
+        ));
+        assert!(matches!(
+            ChangesSince::from_str("2023-12-01T23:55:36 10:00").unwrap(),
+            ChangesSince::Timestamp(t) if t == datetime!(2023-12-01 13:55:36 UTC)
+        ));
+        assert!(ChangesSince::from_str("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_change_log_roundtrip() {
+        let mut change_log = ChangeLog::default();
+        let name = CrateName::new("foo").unwrap();
+
+        change_log.push(
+            ChangeAction::Publish,
+            name.clone(),
+            semver::Version::new(1, 0, 0),
+        );
+        change_log.push(ChangeAction::Yank, name, semver::Version::new(1, 0, 0));
+
+        let change_log = ChangeLog::from_bytes(&change_log.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(
+            change_log
+                .events
+                .iter()
+                .filter(|event| ChangesSince::Seq(1).includes(event))
+                .map(|event| event.seq)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_latest_action() {
+        let mut change_log = ChangeLog::default();
+        let foo = CrateName::new("foo").unwrap();
+        let bar = CrateName::new("bar").unwrap();
+        let v1 = semver::Version::new(1, 0, 0);
+        let v2 = semver::Version::new(2, 0, 0);
+
+        change_log.push(ChangeAction::Publish, foo.clone(), v1.clone());
+        change_log.push(ChangeAction::Publish, foo.clone(), v2.clone());
+        change_log.push(ChangeAction::Yank, foo.clone(), v1.clone());
+
+        assert_eq!(
+            change_log.latest_action(&foo, &v1),
+            Some(ChangeAction::Yank)
+        );
+        assert_eq!(
+            change_log.latest_action(&foo, &v2),
+            Some(ChangeAction::Publish)
+        );
+        assert_eq!(change_log.latest_action(&bar, &v1), None);
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     env,
     fmt::{self, Debug, Formatter},
     net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
@@ -7,7 +8,8 @@ use std::{
 
 use bytesize::ByteSize;
 use config::FileFormat;
-use serde::Deserialize;
+use pasetors::{keys::AsymmetricPublicKey, version3::V3};
+use serde::{de::Error, Deserialize, Deserializer};
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
@@ -16,6 +18,7 @@ pub struct Config {
     pub crates: Crates,
     pub auth: Auth,
     pub storage: Storage,
+    pub access_log: Option<AccessLog>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -60,28 +63,72 @@ fn default_max_publish_size() -> ByteSize {
 pub enum Auth {
     None,
     Token(TokenAuth),
+    Asymmetric(AsymmetricAuth),
 }
 
 #[derive(Clone, Deserialize)]
 pub struct TokenAuth {
     #[serde(deserialize_with = "hex::serde::deserialize")]
     pub token_hash: [u8; 64],
+    /// A human-readable name for the token, used to identify requests in the access log.
+    #[serde(default = "default_token_name")]
+    pub name: String,
+}
+
+fn default_token_name() -> String {
+    String::from("token")
 }
 
 impl Debug for TokenAuth {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("TokenAuth")
             .field("token_hash", &"<REDACTED>")
+            .field("name", &self.name)
             .finish()
     }
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AsymmetricAuth {
+    pub keys: Vec<AsymmetricKey>,
+    /// How long a token is valid for after being issued, to allow for clock skew
+    #[serde(default = "default_max_token_age_secs")]
+    pub max_token_age_secs: u64,
+}
+
+fn default_max_token_age_secs() -> u64 {
+    300
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AsymmetricKey {
+    /// A human-readable name for the owner of the key
+    pub name: String,
+    /// The public key in PASERK format, e.g. `k3.public.<...>`
+    #[serde(deserialize_with = "deserialize_public_key")]
+    pub public_key: AsymmetricPublicKey<V3>,
+    /// If set, tokens must be issued with a matching subject
+    pub subject: Option<String>,
+}
+
+fn deserialize_public_key<'de, D>(deserializer: D) -> Result<AsymmetricPublicKey<V3>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: Cow<'de, str> = Deserialize::deserialize(deserializer)?;
+    AsymmetricPublicKey::<V3>::try_from(s.as_ref())
+        .map_err(|_| D::Error::custom("invalid PASERK public key"))
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
 pub enum Storage {
     Local(LocalStorage),
     #[cfg(feature = "s3")]
     S3(Box<S3Storage>),
+    Encrypted(Box<EncryptedStorage>),
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -90,6 +137,29 @@ pub struct LocalStorage {
     pub path: PathBuf,
 }
 
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedStorage {
+    /// 256-bit AES-GCM key
+    #[serde(deserialize_with = "hex::serde::deserialize")]
+    pub key: [u8; 32],
+    /// Whether to also encrypt index files. Crate files are always encrypted.
+    #[serde(default)]
+    pub encrypt_index: bool,
+    /// The storage the encrypted files are written to
+    pub inner: Storage,
+}
+
+impl Debug for EncryptedStorage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("EncryptedStorage")
+            .field("key", &"<REDACTED>")
+            .field("encrypt_index", &self.encrypt_index)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
 #[derive(Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 #[cfg(feature = "s3")]
@@ -126,6 +196,25 @@ impl Debug for S3Storage {
     }
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessLog {
+    pub format: AccessLogFormat,
+    /// The file to append the access log to. If not set, the access log is written to stdout.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// NCSA Common Log Format
+    Common,
+    /// NCSA Combined Log Format, with the crate name as an extra trailing field
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
 impl Config {
     pub fn load() -> Result<Self, config::ConfigError> {
         let config_path = env::var("QUARTERMASTER_CONFIG_FILE")
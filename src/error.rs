@@ -1,7 +1,7 @@
 use std::fmt::Display;
 
 use axum::{
-    http::StatusCode,
+    http::{header::WWW_AUTHENTICATE, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -52,7 +52,16 @@ impl IntoResponse for ErrorResponse {
             errors: self.errors,
         };
 
-        IntoResponse::into_response((self.status, Json(body)))
+        let mut response = IntoResponse::into_response((self.status, Json(body)));
+
+        // Cargo expects this challenge on all 401 responses from registries
+        if self.status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Cargo"));
+        }
+
+        response
     }
 }
 
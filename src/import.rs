@@ -0,0 +1,640 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use relative_path::{RelativePath, RelativePathBuf};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use stable_eyre::eyre::{self, eyre, WrapErr};
+use tracing::{info, warn};
+
+use crate::{
+    changes::{ChangeAction, ChangeLog},
+    config::Config,
+    crate_name::CrateName,
+    index::{IndexEntry, IndexFile},
+    storage::{self, Storage},
+};
+
+/// Import crates from another registry laid out as an index checkout and a directory of crate files
+#[derive(Debug, clap::Args)]
+pub struct ImportArgs {
+    /// Path to a checkout of the other registry's index
+    #[arg(long)]
+    index: PathBuf,
+
+    /// Path to the directory containing the other registry's `.crate` files
+    #[arg(long)]
+    crates: PathBuf,
+
+    /// Validate the crates without writing anything to the storage
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+struct ImportStats {
+    imported: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+/// NOTE: Quartermaster should not be serving requests while an import is running, since the
+/// import doesn't take part in the server's locking and could overwrite concurrent publishes.
+pub async fn run(config: &Config, args: &ImportArgs) -> eyre::Result<()> {
+    let storage = Storage::new(&config.storage).await?;
+    let mut change_log = storage.read_change_log().await?;
+
+    if args.dry_run {
+        info!("Dry run, nothing will be written to the storage");
+    }
+
+    let index_paths = find_index_files(&args.index)
+        .wrap_err_with(|| format!("Failed to read index at {}", args.index.display()))?;
+
+    info!("Found {} index files", index_paths.len());
+
+    let mut stats = ImportStats::default();
+
+    for index_path in index_paths {
+        let crate_name = match CrateName::from_index_path(&index_path) {
+            Ok(crate_name) => crate_name,
+            Err(e) => {
+                warn!("Skipping {index_path}, which is not a valid index file path: {e}");
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        if let Err(e) = import_crate(
+            &storage,
+            args,
+            &crate_name,
+            &index_path,
+            &mut change_log,
+            &mut stats,
+        )
+        .await
+        {
+            warn!("Failed to import crate {crate_name}: {e:#}");
+            stats.failed += 1;
+        }
+    }
+
+    info!(
+        "Imported {} versions, skipped {}, failed {}",
+        stats.imported, stats.skipped, stats.failed
+    );
+
+    if stats.failed > 0 {
+        return Err(eyre!("Import finished with {} failures", stats.failed));
+    }
+
+    Ok(())
+}
+
+async fn import_crate(
+    storage: &Storage,
+    args: &ImportArgs,
+    crate_name: &CrateName,
+    index_path: &RelativePath,
+    change_log: &mut ChangeLog,
+    stats: &mut ImportStats,
+) -> eyre::Result<()> {
+    let contents = tokio::fs::read(index_path.to_path(&args.index)).await?;
+    let contents = std::str::from_utf8(&contents)?;
+
+    let mut index_file = match storage.read_index_file(crate_name).await {
+        Ok(index_file) => index_file,
+        Err(storage::Error::NotFound) => IndexFile::default(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut modified = false;
+    let mut events = Vec::new();
+
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: Map<String, Value> = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Failed to parse line in the index file for {crate_name}: {e}");
+                stats.failed += 1;
+                continue;
+            }
+        };
+
+        // The raw name and version are kept around to locate the crate file, since the
+        // original registry might not have normalized them like Quartermaster does
+        let raw_name = entry
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned();
+        let raw_vers = entry
+            .get("vers")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_owned();
+
+        let index_entry: IndexEntry =
+            match serde_json::from_value(Value::Object(normalize_entry(entry))) {
+                Ok(index_entry) => index_entry,
+                Err(e) => {
+                    warn!("Failed to parse index entry for {raw_name} version {raw_vers}: {e}");
+                    stats.failed += 1;
+                    continue;
+                }
+            };
+
+        if &index_entry.name != crate_name {
+            warn!("Index entry for {raw_name} version {raw_vers} found in the index file for {crate_name}");
+            stats.failed += 1;
+            continue;
+        }
+
+        if let Some(existing) = index_file
+            .entries
+            .iter()
+            .find(|existing| existing.vers == index_entry.vers)
+        {
+            if existing.cksum.eq_ignore_ascii_case(&index_entry.cksum) {
+                info!("Crate {crate_name} version {raw_vers} already exists, skipping");
+                stats.skipped += 1;
+
+                // A previous import might have failed to write the change log
+                if change_log
+                    .latest_action(crate_name, &existing.vers)
+                    .is_none()
+                {
+                    push_events(&mut events, existing);
+                }
+            } else {
+                warn!("Crate {crate_name} version {raw_vers} already exists with a different checksum");
+                stats.failed += 1;
+            }
+
+            continue;
+        }
+
+        let Some(crate_data) = read_crate_file(&args.crates, &raw_name, &raw_vers).await? else {
+            warn!("Crate file for {raw_name} version {raw_vers} not found");
+            stats.failed += 1;
+            continue;
+        };
+
+        let cksum = hex::encode(Sha256::digest(&crate_data));
+
+        if !cksum.eq_ignore_ascii_case(&index_entry.cksum) {
+            warn!(
+                "Checksum mismatch for {raw_name} version {raw_vers}: index has {}, crate file has {cksum}",
+                index_entry.cksum
+            );
+            stats.failed += 1;
+            continue;
+        }
+
+        if !args.dry_run {
+            storage
+                .write_crate_file(crate_name, &index_entry.vers, &crate_data)
+                .await?;
+        }
+
+        info!("Imported crate {crate_name} version {raw_vers}");
+
+        push_events(&mut events, &index_entry);
+        index_file.entries.push(index_entry);
+        stats.imported += 1;
+        modified = true;
+    }
+
+    if args.dry_run {
+        return Ok(());
+    }
+
+    if modified {
+        storage.write_index_file(crate_name, &index_file).await?;
+    }
+
+    // The change log is only updated once the index has been written, so that it never contains
+    // versions which don't exist
+    if !events.is_empty() {
+        for (action, vers) in events {
+            change_log.push(action, crate_name.clone(), vers);
+        }
+
+        storage.write_change_log(change_log).await?;
+    }
+
+    Ok(())
+}
+
+/// Queue the change log events for an imported version, which is published and possibly yanked
+fn push_events(events: &mut Vec<(ChangeAction, semver::Version)>, entry: &IndexEntry) {
+    events.push((ChangeAction::Publish, entry.vers.clone()));
+
+    if entry.yanked {
+        events.push((ChangeAction::Yank, entry.vers.clone()));
+    }
+}
+
+/// Recursively list all files in the index, relative to its root.
+/// Hidden files and directories (like `.git`) and the `config.json` file are ignored.
+fn find_index_files(root: &Path) -> io::Result<Vec<RelativePathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![RelativePathBuf::new()];
+
+    while let Some(dir) = dirs.pop() {
+        for dir_entry in std::fs::read_dir(dir.to_path(root))? {
+            let dir_entry = dir_entry?;
+            let file_name = dir_entry.file_name();
+
+            let Some(file_name) = file_name.to_str() else {
+                warn!("Skipping non-UTF-8 path in index: {:?}", dir_entry.path());
+                continue;
+            };
+
+            if file_name.starts_with('.') || (dir.as_str().is_empty() && file_name == "config.json")
+            {
+                continue;
+            }
+
+            let path = dir.join(file_name);
+
+            if dir_entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Convert an index entry from another registry to the format Quartermaster uses.
+///
+/// Quartermaster assumes all entries use version 2 of the index format, so `features2` is merged
+/// into `features`. Dependencies with a missing `kind`, which exist in the crates.io index due to
+/// old bugs, are treated as normal dependencies.
+fn normalize_entry(mut entry: Map<String, Value>) -> Map<String, Value> {
+    entry.remove("v");
+
+    if let Some(Value::Object(features2)) = entry.remove("features2") {
+        match entry.get_mut("features") {
+            Some(Value::Object(features)) => features.extend(features2),
+            _ => {
+                entry.insert(String::from("features"), Value::Object(features2));
+            }
+        }
+    }
+
+    if let Some(Value::Array(deps)) = entry.get_mut("deps") {
+        for dep in deps.iter_mut().filter_map(Value::as_object_mut) {
+            if matches!(dep.get("kind"), None | Some(Value::Null)) {
+                dep.insert(String::from("kind"), Value::from("normal"));
+            }
+        }
+    }
+
+    entry
+}
+
+/// Layouts used by other registries to store crate files, relative to their crate directory
+fn crate_file_candidates(name: &str, vers: &str) -> Vec<PathBuf> {
+    let mut names = vec![name.to_owned()];
+    if name.to_lowercase() != name {
+        names.push(name.to_lowercase());
+    }
+
+    let mut candidates = Vec::new();
+
+    for name in names {
+        let file_name = format!("{name}-{vers}.crate");
+
+        candidates.extend([
+            // Flat directory, e.g. kellnr and alexandrie
+            PathBuf::from(&file_name),
+            // Grouped by crate, e.g. crates.io mirrors
+            PathBuf::from(&name).join(&file_name),
+            PathBuf::from(&name).join(vers).join(&file_name),
+            // Download API paths, e.g. meuse or a mirror of crates.io download URLs
+            PathBuf::from(&name).join(vers).join("download"),
+            // Quartermaster's own layout
+            PathBuf::from(&name)
+                .join(vers)
+                .join(format!("{name}.crate")),
+        ]);
+    }
+
+    candidates
+}
+
+async fn read_crate_file(crates: &Path, name: &str, vers: &str) -> io::Result<Option<Vec<u8>>> {
+    for candidate in crate_file_candidates(name, vers) {
+        match tokio::fs::read(crates.join(candidate)).await {
+            Ok(data) => return Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::config;
+
+    struct Fixture {
+        _dir: TempDir,
+        storage: Storage,
+        args: ImportArgs,
+        name: CrateName,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let dir = TempDir::new().unwrap();
+
+            for subdir in ["storage", "index", "crates"] {
+                std::fs::create_dir(dir.path().join(subdir)).unwrap();
+            }
+
+            let storage = Storage::new(&config::Storage::Local(config::LocalStorage {
+                path: dir.path().join("storage"),
+            }))
+            .await
+            .unwrap();
+
+            let args = ImportArgs {
+                index: dir.path().join("index"),
+                crates: dir.path().join("crates"),
+                dry_run: false,
+            };
+
+            Self {
+                _dir: dir,
+                storage,
+                args,
+                name: CrateName::new("foo").unwrap(),
+            }
+        }
+
+        /// Write the other registry's index file with a valid entry and crate file for each
+        /// version, followed by some extra lines
+        fn write_registry(&self, versions: &[(&str, bool)], lines: &[String]) {
+            let mut contents = String::new();
+
+            for (vers, yanked) in versions {
+                let crate_data = format!("foo {vers}");
+                std::fs::write(
+                    self.args.crates.join(format!("foo-{vers}.crate")),
+                    &crate_data,
+                )
+                .unwrap();
+
+                let cksum = hex::encode(Sha256::digest(&crate_data));
+                contents.push_str(&entry(vers, &cksum, *yanked));
+                contents.push('\n');
+            }
+
+            for line in lines {
+                contents.push_str(line);
+                contents.push('\n');
+            }
+
+            let index_path = self.name.index_path().to_path(&self.args.index);
+            std::fs::create_dir_all(index_path.parent().unwrap()).unwrap();
+            std::fs::write(index_path, contents).unwrap();
+        }
+
+        async fn import(&self) -> ImportStats {
+            let mut change_log = self.storage.read_change_log().await.unwrap();
+            let mut stats = ImportStats::default();
+
+            import_crate(
+                &self.storage,
+                &self.args,
+                &self.name,
+                &self.name.index_path(),
+                &mut change_log,
+                &mut stats,
+            )
+            .await
+            .unwrap();
+
+            stats
+        }
+
+        async fn index_versions(&self) -> Vec<(String, bool)> {
+            match self.storage.read_index_file(&self.name).await {
+                Ok(index_file) => index_file
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.vers.to_string(), entry.yanked))
+                    .collect(),
+                Err(storage::Error::NotFound) => Vec::new(),
+                Err(e) => panic!("{e}"),
+            }
+        }
+
+        async fn change_log_events(&self) -> Vec<(ChangeAction, String)> {
+            self.storage
+                .read_change_log()
+                .await
+                .unwrap()
+                .events
+                .into_iter()
+                .map(|event| (event.action, event.vers.to_string()))
+                .collect()
+        }
+    }
+
+    fn entry(vers: &str, cksum: &str, yanked: bool) -> String {
+        json!({
+            "name": "foo",
+            "vers": vers,
+            "deps": [],
+            "cksum": cksum,
+            "features": {},
+            "yanked": yanked,
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_import_crate() {
+        let fixture = Fixture::new().await;
+        fixture.write_registry(
+            &[("1.0.0", false), ("1.1.0", true)],
+            &[
+                String::from("{not json"),
+                entry("1.2.0", "00", false),
+                entry("2.0.0", "00", false),
+            ],
+        );
+        // The crate file doesn't match the checksum in the index
+        std::fs::write(fixture.args.crates.join("foo-1.2.0.crate"), "foo 1.2.0").unwrap();
+
+        let stats = fixture.import().await;
+
+        // The malformed line, the checksum mismatch and the missing crate file fail
+        assert_eq!((stats.imported, stats.skipped, stats.failed), (2, 0, 3));
+        assert_eq!(
+            fixture.index_versions().await,
+            vec![
+                (String::from("1.0.0"), false),
+                (String::from("1.1.0"), true)
+            ]
+        );
+        assert_eq!(
+            fixture.change_log_events().await,
+            vec![
+                (ChangeAction::Publish, String::from("1.0.0")),
+                (ChangeAction::Publish, String::from("1.1.0")),
+                (ChangeAction::Yank, String::from("1.1.0")),
+            ]
+        );
+        fixture
+            .storage
+            .read_file(&storage::crate_file_path(
+                &fixture.name,
+                &semver::Version::new(1, 0, 0),
+            ))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_crate_existing_versions() {
+        let fixture = Fixture::new().await;
+        fixture.write_registry(&[("1.0.0", false)], &[]);
+        fixture.import().await;
+
+        // Importing again skips versions which already exist with the same checksum
+        let stats = fixture.import().await;
+
+        assert_eq!((stats.imported, stats.skipped, stats.failed), (0, 1, 0));
+        assert_eq!(fixture.change_log_events().await.len(), 1);
+
+        // A version which already exists with a different checksum fails
+        fixture.write_registry(&[], &[entry("1.0.0", "00", false)]);
+        let stats = fixture.import().await;
+
+        assert_eq!((stats.imported, stats.skipped, stats.failed), (0, 0, 1));
+        assert_eq!(fixture.change_log_events().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_crate_records_missing_events() {
+        let fixture = Fixture::new().await;
+        fixture.write_registry(&[("1.0.0", true)], &[]);
+        fixture.import().await;
+
+        // Simulate a previous import which failed to write the change log
+        fixture
+            .storage
+            .write_change_log(&ChangeLog::default())
+            .await
+            .unwrap();
+
+        let stats = fixture.import().await;
+
+        assert_eq!((stats.imported, stats.skipped, stats.failed), (0, 1, 0));
+        assert_eq!(
+            fixture.change_log_events().await,
+            vec![
+                (ChangeAction::Publish, String::from("1.0.0")),
+                (ChangeAction::Yank, String::from("1.0.0")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_crate_dry_run() {
+        let mut fixture = Fixture::new().await;
+        fixture.args.dry_run = true;
+        fixture.write_registry(&[("1.0.0", false)], &[]);
+
+        let stats = fixture.import().await;
+
+        assert_eq!((stats.imported, stats.skipped, stats.failed), (1, 0, 0));
+        assert!(fixture.index_versions().await.is_empty());
+        assert!(fixture.change_log_events().await.is_empty());
+        assert!(matches!(
+            fixture
+                .storage
+                .read_file(&storage::crate_file_path(
+                    &fixture.name,
+                    &semver::Version::new(1, 0, 0),
+                ))
+                .await,
+            Err(storage::Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_normalize_entry() {
+        let Value::Object(entry) = json!({
+            "name": "foo",
+            "vers": "1.0.0",
+            "deps": [{"name": "bar", "req": "^1", "features": [], "optional": true, "default_features": true, "kind": null}],
+            "cksum": "00",
+            "features": {"default": ["std"]},
+            "features2": {"bar": ["dep:bar"]},
+            "yanked": false,
+            "v": 2,
+        }) else {
+            unreachable!()
+        };
+
+        let entry = normalize_entry(entry);
+
+        assert_eq!(entry.get("v"), None);
+        assert_eq!(entry.get("features2"), None);
+        assert_eq!(
+            entry["features"],
+            json!({"default": ["std"], "bar": ["dep:bar"]})
+        );
+        assert_eq!(entry["deps"][0]["kind"], "normal");
+
+        serde_json::from_value::<IndexEntry>(Value::Object(entry)).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_entry_without_features() {
+        let Value::Object(entry) = json!({
+            "name": "foo",
+            "vers": "1.0.0",
+            "deps": [],
+            "cksum": "00",
+            "features2": {"bar": ["dep:bar"]},
+            "yanked": false,
+        }) else {
+            unreachable!()
+        };
+
+        let entry = normalize_entry(entry);
+
+        assert_eq!(entry.get("features2"), None);
+        assert_eq!(entry["features"], json!({"bar": ["dep:bar"]}));
+
+        serde_json::from_value::<IndexEntry>(Value::Object(entry)).unwrap();
+    }
+
+    #[test]
+    fn test_crate_file_candidates() {
+        let candidates = crate_file_candidates("Foo", "1.0.0");
+
+        assert!(candidates.contains(&PathBuf::from("Foo-1.0.0.crate")));
+        assert!(candidates.contains(&PathBuf::from("foo/1.0.0/download")));
+        assert!(candidates.contains(&PathBuf::from("foo/1.0.0/foo.crate")));
+    }
+}
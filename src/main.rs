@@ -1,15 +1,18 @@
-use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf, sync::Arc};
 
-use auth::Authorization;
+use auth::{Authorization, Mutation};
 use axum::{
     body::{Body, HttpBody},
-    extract::State,
+    extract::{Query, State},
     http::{StatusCode, Uri},
+    middleware,
     response::IntoResponse,
     routing::{get, put},
-    Json, Router,
+    Extension, Json, Router,
 };
 use axum_extra::routing::{RouterExt, TypedPath};
+use changes::{ChangeAction, ChangeEvent, ChangesSince};
+use clap::{Parser, Subcommand};
 use error::{ErrorResponse, ResponseError};
 use feature_name::FeatureName;
 use http_body_util::{BodyExt, LengthLimitError, Limited};
@@ -24,20 +27,40 @@ use tracing::{debug, info, level_filters::LevelFilter, warn};
 use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
 use url::Url;
 
+mod access_log;
 mod auth;
+mod changes;
 mod config;
 mod crate_name;
 mod error;
 mod feature_name;
+mod import;
 mod index;
 mod storage;
 
 use crate::{config::Config, crate_name::CrateName};
 
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the registry. This is the default if no command is given.
+    Serve,
+    /// Import crates from another registry into the configured storage.
+    Import(import::ImportArgs),
+}
+
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     stable_eyre::install()?;
 
+    let cli = Cli::parse();
+
     tracing_subscriber::fmt()
         .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
         .with_env_filter(
@@ -49,10 +72,22 @@ async fn main() -> eyre::Result<()> {
 
     let config = Config::load()?;
 
-    let auth = auth::Auth::new(&config.auth).await?;
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Import(args) => import::run(&config, &args).await,
+    }
+}
+
+async fn serve(config: Config) -> eyre::Result<()> {
+    let auth = auth::Auth::new(&config.auth, &config.server.root_url).await?;
     let storage = storage::Storage::new(&config.storage).await?;
     let lock = RwLock::new(());
 
+    let access_log = match &config.access_log {
+        Some(access_log) => Some(access_log::AccessLog::new(access_log).await?),
+        None => None,
+    };
+
     let bind = config.server.bind.clone();
 
     let state = Arc::new(AppState {
@@ -60,6 +95,7 @@ async fn main() -> eyre::Result<()> {
         auth,
         storage,
         lock,
+        access_log,
     });
 
     info!(
@@ -78,11 +114,20 @@ async fn main() -> eyre::Result<()> {
         .route("/api/v1/crates/new", put(put_publish_crate))
         .typed_delete(delete_yank_crate)
         .typed_put(put_unyank_crate)
+        .route("/api/v1/changes", get(get_changes))
         .fallback(fallback)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            access_log::middleware,
+        ))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(bind.as_slice()).await?;
-    axum::serve(listener, router).await?;
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     println!("Hello, world!");
 
@@ -94,6 +139,7 @@ struct AppState {
     auth: auth::Auth,
     storage: storage::Storage,
     lock: RwLock<()>,
+    access_log: Option<access_log::AccessLog>,
 }
 
 #[tracing::instrument(skip_all)]
@@ -122,8 +168,8 @@ async fn get_index_file(
     GetIndexFile { path }: GetIndexFile,
     State(state): State<Arc<AppState>>,
     authorization: Option<Authorization>,
-) -> Result<Vec<u8>, ErrorResponse> {
-    state
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let token_name = state
         .auth
         .authorize(authorization.as_ref().map(|a| a.token()))?;
 
@@ -135,9 +181,11 @@ async fn get_index_file(
         state.storage.read_index_file(&crate_name).await?
     };
 
-    index_file
+    let bytes = index_file
         .to_bytes()
-        .map_err(ErrorResponse::internal_server_error)
+        .map_err(ErrorResponse::internal_server_error)?;
+
+    Ok((token_name.map(Extension), Extension(crate_name), bytes))
 }
 
 #[derive(Debug, Deserialize, TypedPath)]
@@ -156,7 +204,7 @@ async fn get_download_crate(
     State(state): State<Arc<AppState>>,
     authorization: Option<Authorization>,
 ) -> Result<impl IntoResponse, ErrorResponse> {
-    state
+    let token_name = state
         .auth
         .authorize(authorization.as_ref().map(|a| a.token()))?;
 
@@ -170,7 +218,7 @@ async fn get_download_crate(
 
     // TODO: Configurable cache control headers?
 
-    Ok(body)
+    Ok((token_name.map(Extension), Extension(crate_name), body))
 }
 
 #[derive(Deserialize)]
@@ -282,10 +330,12 @@ async fn put_publish_crate(
     State(state): State<Arc<AppState>>,
     authorization: Option<Authorization>,
     body: Body,
-) -> Result<Json<PublishResponse>, ErrorResponse> {
-    state
-        .auth
-        .authorize(authorization.as_ref().map(|a| a.token()))?;
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let token = authorization.as_ref().map(|a| a.token());
+
+    // Reject invalid tokens before receiving the body. The token is checked against the actual
+    // crate once the body has been parsed.
+    state.auth.authenticate(token)?;
 
     let mut warnings = Vec::new();
 
@@ -366,9 +416,21 @@ async fn put_publish_crate(
     let checksum_array: &[u8] = checksum.as_ref();
     let cksum = hex::encode(checksum_array);
 
+    let token_name = state.auth.authorize_mutation(
+        token,
+        &Mutation::Publish {
+            name: &crate_name,
+            vers: &crate_version,
+            cksum: &cksum,
+        },
+    )?;
+
     {
         let _guard = state.lock.write().await;
 
+        // Load the change log first, so that nothing is written if it can't be read
+        let mut change_log = state.storage.read_change_log().await?;
+
         // Load the index (if it exists) and check that this crate version doesn't already exist
         info!("Checking crate version doesn't exist");
 
@@ -383,6 +445,20 @@ async fn put_publish_crate(
             .iter()
             .any(|entry| entry.vers == crate_version)
         {
+            // The change log is written after the index, so a previous publish of this version
+            // might have succeeded without being recorded. Retrying it records the missing event.
+            if change_log
+                .latest_action(&crate_name, &crate_version)
+                .is_none()
+            {
+                change_log.push(
+                    ChangeAction::Publish,
+                    crate_name.clone(),
+                    crate_version.clone(),
+                );
+                state.storage.write_change_log(&change_log).await?;
+            }
+
             return Err(ErrorResponse {
                 status: StatusCode::BAD_REQUEST,
                 errors: vec![ResponseError {
@@ -440,17 +516,28 @@ async fn put_publish_crate(
             .storage
             .write_index_file(&crate_name, &index_file)
             .await?;
+
+        change_log.push(
+            ChangeAction::Publish,
+            crate_name.clone(),
+            crate_version.clone(),
+        );
+        state.storage.write_change_log(&change_log).await?;
     }
 
     info!("Crate {crate_name} version {crate_version} successfully published");
 
-    Ok(Json(PublishResponse {
-        warnings: PublishWarnings {
-            invalid_categories: Vec::new(),
-            invalid_badges: Vec::new(),
-            other: warnings,
-        },
-    }))
+    Ok((
+        token_name.map(Extension),
+        Extension(crate_name),
+        Json(PublishResponse {
+            warnings: PublishWarnings {
+                invalid_categories: Vec::new(),
+                invalid_badges: Vec::new(),
+                other: warnings,
+            },
+        }),
+    ))
 }
 
 #[derive(Debug, Deserialize, TypedPath)]
@@ -473,18 +560,23 @@ async fn delete_yank_crate(
     }: DeleteYankCrate,
     State(state): State<Arc<AppState>>,
     authorization: Option<Authorization>,
-) -> Result<Json<YankResponse>, ErrorResponse> {
-    state
-        .auth
-        .authorize(authorization.as_ref().map(|a| a.token()))?;
-
+) -> Result<impl IntoResponse, ErrorResponse> {
     let crate_name = CrateName::new(&crate_name).map_err(ErrorResponse::not_found)?;
     let version = semver::Version::parse(&version).map_err(ErrorResponse::not_found)?;
 
+    let token_name = state.auth.authorize_mutation(
+        authorization.as_ref().map(|a| a.token()),
+        &Mutation::Yank {
+            name: &crate_name,
+            vers: &version,
+        },
+    )?;
+
     {
         let _guard = state.lock.write().await;
 
         let mut index_file = state.storage.read_index_file(&crate_name).await?;
+        let mut change_log = state.storage.read_change_log().await?;
 
         let index_entry = index_file
             .entries
@@ -492,16 +584,37 @@ async fn delete_yank_crate(
             .find(|entry| entry.vers == version)
             .ok_or_else(|| ErrorResponse::from_status(StatusCode::NOT_FOUND))?;
 
-        index_entry.yanked = true;
+        let changed = !index_entry.yanked;
 
-        state
-            .storage
-            .write_index_file(&crate_name, &index_file)
-            .await?;
+        if changed {
+            index_entry.yanked = true;
+
+            state
+                .storage
+                .write_index_file(&crate_name, &index_file)
+                .await?;
+
+            info!("Crate {crate_name} version {version} yanked");
+        } else {
+            info!("Crate {crate_name} version {version} is already yanked");
+        }
+
+        // Don't record duplicate events if nothing changed. The change log is written after the
+        // index though, so a previous yank might have succeeded without being recorded, in which
+        // case retrying it records the missing event.
+        let latest_action = change_log.latest_action(&crate_name, &version);
+
+        if changed || latest_action.is_some_and(|action| action != ChangeAction::Yank) {
+            change_log.push(ChangeAction::Yank, crate_name.clone(), version.clone());
+            state.storage.write_change_log(&change_log).await?;
+        }
     }
 
-    info!("Crate {crate_name} version {version} yanked");
-    Ok(Json(YankResponse { ok: true }))
+    Ok((
+        token_name.map(Extension),
+        Extension(crate_name),
+        Json(YankResponse { ok: true }),
+    ))
 }
 
 #[derive(Debug, Deserialize, TypedPath)]
@@ -524,18 +637,23 @@ async fn put_unyank_crate(
     }: PutUnyankCrate,
     State(state): State<Arc<AppState>>,
     authorization: Option<Authorization>,
-) -> Result<Json<UnyankResponse>, ErrorResponse> {
-    state
-        .auth
-        .authorize(authorization.as_ref().map(|a| a.token()))?;
-
+) -> Result<impl IntoResponse, ErrorResponse> {
     let crate_name = CrateName::new(&crate_name).map_err(ErrorResponse::not_found)?;
     let version = semver::Version::parse(&version).map_err(ErrorResponse::not_found)?;
 
+    let token_name = state.auth.authorize_mutation(
+        authorization.as_ref().map(|a| a.token()),
+        &Mutation::Unyank {
+            name: &crate_name,
+            vers: &version,
+        },
+    )?;
+
     {
         let _guard = state.lock.write().await;
 
         let mut index_file = state.storage.read_index_file(&crate_name).await?;
+        let mut change_log = state.storage.read_change_log().await?;
 
         let index_entry = index_file
             .entries
@@ -543,16 +661,87 @@ async fn put_unyank_crate(
             .find(|entry| entry.vers == version)
             .ok_or_else(|| ErrorResponse::from_status(StatusCode::NOT_FOUND))?;
 
-        index_entry.yanked = false;
+        let changed = index_entry.yanked;
 
-        state
-            .storage
-            .write_index_file(&crate_name, &index_file)
-            .await?;
+        if changed {
+            index_entry.yanked = false;
+
+            state
+                .storage
+                .write_index_file(&crate_name, &index_file)
+                .await?;
+
+            info!("Crate {crate_name} version {version} unyanked");
+        } else {
+            info!("Crate {crate_name} version {version} is already unyanked");
+        }
+
+        // Don't record duplicate events if nothing changed. The change log is written after the
+        // index though, so a previous unyank might have succeeded without being recorded, in which
+        // case retrying it records the missing event.
+        let latest_action = change_log.latest_action(&crate_name, &version);
+
+        if changed || latest_action == Some(ChangeAction::Yank) {
+            change_log.push(ChangeAction::Unyank, crate_name.clone(), version.clone());
+            state.storage.write_change_log(&change_log).await?;
+        }
     }
 
-    info!("Crate {crate_name} version {version} unyanked");
-    Ok(Json(UnyankResponse { ok: true }))
+    Ok((
+        token_name.map(Extension),
+        Extension(crate_name),
+        Json(UnyankResponse { ok: true }),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangesQuery {
+    /// Only return changes after this sequence number or timestamp
+    #[serde(default)]
+    since: ChangesSince,
+    /// Only return changes to this crate
+    name: Option<CrateName>,
+    /// Maximum number of changes to return, capped at `changes::MAX_LIMIT`
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ChangesResponse {
+    changes: Vec<ChangeEvent>,
+}
+
+#[tracing::instrument(skip(state, authorization))]
+async fn get_changes(
+    Query(query): Query<ChangesQuery>,
+    State(state): State<Arc<AppState>>,
+    authorization: Option<Authorization>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let token_name = state
+        .auth
+        .authorize(authorization.as_ref().map(|a| a.token()))?;
+
+    let change_log = {
+        let _guard = state.lock.read().await;
+        state.storage.read_change_log().await?
+    };
+
+    let changes = change_log
+        .events
+        .into_iter()
+        .filter(|event| query.since.includes(event))
+        .filter(|event| match &query.name {
+            Some(name) => &event.name == name,
+            None => true,
+        })
+        .take(
+            query
+                .limit
+                .unwrap_or(changes::DEFAULT_LIMIT)
+                .min(changes::MAX_LIMIT),
+        )
+        .collect();
+
+    Ok((token_name.map(Extension), Json(ChangesResponse { changes })))
 }
 
 #[tracing::instrument]
@@ -1,9 +1,11 @@
 use std::{io, str::FromStr};
 
 use axum::{body::Body, http::StatusCode};
+use relative_path::{RelativePath, RelativePathBuf};
 use tracing::instrument;
 
 use crate::{
+    changes::{ChangeLog, ChangeLogError},
     crate_name::CrateName,
     error::{ErrorResponse, ResponseError},
     index::{IndexFile, IndexFileError},
@@ -12,12 +14,14 @@ use crate::{
 #[cfg(feature = "s3")]
 pub mod s3;
 
+pub mod encrypted;
 pub mod local;
 
 pub enum Storage {
     Local(local::LocalStorage),
     #[cfg(feature = "s3")]
-    S3(s3::S3Storage),
+    S3(Box<s3::S3Storage>),
+    Encrypted(Box<encrypted::EncryptedStorage>),
 }
 
 impl Storage {
@@ -27,7 +31,10 @@ impl Storage {
                 Ok(Self::Local(local::LocalStorage::new(local).await?))
             }
             #[cfg(feature = "s3")]
-            crate::config::Storage::S3(s3) => Ok(Self::S3(s3::S3Storage::new(s3)?)),
+            crate::config::Storage::S3(s3) => Ok(Self::S3(Box::new(s3::S3Storage::new(s3)?))),
+            crate::config::Storage::Encrypted(encrypted) => Ok(Self::Encrypted(Box::new(
+                encrypted::EncryptedStorage::new(encrypted).await?,
+            ))),
         }
     }
 
@@ -38,6 +45,7 @@ impl Storage {
             Storage::Local(local) => local.read_index_file(name).await,
             #[cfg(feature = "s3")]
             Storage::S3(s3) => s3.read_index_file(name).await,
+            Storage::Encrypted(encrypted) => encrypted.read_index_file(name).await,
         }
     }
 
@@ -51,6 +59,7 @@ impl Storage {
             Storage::Local(local) => local.read_crate_file(name, version).await,
             #[cfg(feature = "s3")]
             Storage::S3(s3) => s3.read_crate_file(name, version).await,
+            Storage::Encrypted(encrypted) => encrypted.read_crate_file(name, version).await,
         }
     }
 
@@ -64,6 +73,7 @@ impl Storage {
             Storage::Local(local) => local.write_index_file(name, index_file).await,
             #[cfg(feature = "s3")]
             Storage::S3(s3) => s3.write_index_file(name, index_file).await,
+            Storage::Encrypted(encrypted) => encrypted.write_index_file(name, index_file).await,
         }
     }
 
@@ -78,8 +88,62 @@ impl Storage {
             Storage::Local(local) => local.write_crate_file(name, version, contents).await,
             #[cfg(feature = "s3")]
             Storage::S3(s3) => s3.write_crate_file(name, version, contents).await,
+            Storage::Encrypted(encrypted) => {
+                encrypted.write_crate_file(name, version, contents).await
+            }
+        }
+    }
+
+    /// Read the change log. A missing change log is treated as empty, since it's only created on
+    /// the first publish.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn read_change_log(&self) -> Result<ChangeLog, Error> {
+        match self.read_file(&change_log_path()).await {
+            Ok(contents) => ChangeLog::from_bytes(&contents).map_err(Error::ChangeLog),
+            Err(Error::NotFound) => Ok(ChangeLog::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[instrument(level = "debug", skip(self, change_log))]
+    pub async fn write_change_log(&self, change_log: &ChangeLog) -> Result<(), Error> {
+        let contents = change_log.to_bytes().map_err(Error::ChangeLog)?;
+        self.write_file(&change_log_path(), &contents).await
+    }
+
+    /// Read an arbitrary file, relative to the root of the storage
+    #[instrument(level = "debug", skip(self))]
+    pub async fn read_file(&self, path: &RelativePath) -> Result<Vec<u8>, Error> {
+        match self {
+            Storage::Local(local) => local.read_file(path).await,
+            #[cfg(feature = "s3")]
+            Storage::S3(s3) => s3.read_file(path).await,
+            Storage::Encrypted(encrypted) => encrypted.read_file(path).await,
         }
     }
+
+    /// Write an arbitrary file, relative to the root of the storage
+    #[instrument(level = "debug", skip(self, contents))]
+    pub async fn write_file(&self, path: &RelativePath, contents: &[u8]) -> Result<(), Error> {
+        match self {
+            Storage::Local(local) => local.write_file(path, contents).await,
+            #[cfg(feature = "s3")]
+            Storage::S3(s3) => s3.write_file(path, contents).await,
+            Storage::Encrypted(encrypted) => encrypted.write_file(path, contents).await,
+        }
+    }
+}
+
+pub fn index_file_path(name: &CrateName) -> RelativePathBuf {
+    RelativePathBuf::from("index").join(name.index_path())
+}
+
+pub fn change_log_path() -> RelativePathBuf {
+    RelativePathBuf::from("changes.jsonl")
+}
+
+pub fn crate_file_path(name: &CrateName, version: &semver::Version) -> RelativePathBuf {
+    RelativePathBuf::from("crates").join(name.crate_path(version))
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -94,6 +158,10 @@ pub enum Error {
     Io(#[source] io::Error),
     #[error("Error parsing index file")]
     IndexFile(#[source] IndexFileError),
+    #[error("Error parsing change log")]
+    ChangeLog(#[source] ChangeLogError),
+    #[error("Error encrypting or decrypting file")]
+    Encryption,
 
     #[cfg(feature = "s3")]
     #[error("S3 error")]
@@ -117,12 +185,14 @@ impl From<Error> for ErrorResponse {
                     detail: String::from("Crate not found"),
                 }],
             },
-            Error::Io(_) | Error::IndexFile(_) => ErrorResponse {
-                status: StatusCode::INTERNAL_SERVER_ERROR,
-                errors: vec![ResponseError {
-                    detail: String::from("Storage error"),
-                }],
-            },
+            Error::Io(_) | Error::IndexFile(_) | Error::ChangeLog(_) | Error::Encryption => {
+                ErrorResponse {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    errors: vec![ResponseError {
+                        detail: String::from("Storage error"),
+                    }],
+                }
+            }
 
             #[cfg(feature = "s3")]
             Error::S3(_) | Error::S3Credentials(_) | Error::S3Region(_) => ErrorResponse {
@@ -0,0 +1,216 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use axum::body::Body;
+use futures::future::BoxFuture;
+use relative_path::RelativePath;
+use tracing::info;
+
+use crate::{crate_name::CrateName, index::IndexFile};
+
+use super::{crate_file_path, index_file_path, Error, Storage};
+
+/// Size of the AES-GCM nonce prepended to every encrypted file
+const NONCE_SIZE: usize = 12;
+
+/// Storage wrapper which encrypts files with AES-256-GCM before handing them to the inner storage.
+///
+/// Each file is stored as a random nonce followed by the ciphertext. The path of the file is used
+/// as associated data, so that an encrypted file can't be swapped with another one in the storage.
+///
+/// NOTE: The methods here return boxed futures because they call back into `Storage`, which would
+/// otherwise make the futures infinitely sized.
+pub struct EncryptedStorage {
+    cipher: Aes256Gcm,
+    encrypt_index: bool,
+    inner: Storage,
+}
+
+impl EncryptedStorage {
+    pub fn new(config: &crate::config::EncryptedStorage) -> BoxFuture<'_, Result<Self, Error>> {
+        Box::pin(async move {
+            info!("Using encrypted storage");
+
+            if !config.encrypt_index {
+                info!("Index files will not be encrypted");
+            }
+
+            Ok(Self {
+                cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&config.key)),
+                encrypt_index: config.encrypt_index,
+                inner: Storage::new(&config.inner).await?,
+            })
+        })
+    }
+
+    pub fn read_index_file<'a>(
+        &'a self,
+        name: &'a CrateName,
+    ) -> BoxFuture<'a, Result<IndexFile, Error>> {
+        Box::pin(async move {
+            if !self.encrypt_index {
+                return self.inner.read_index_file(name).await;
+            }
+
+            let contents = self.read_encrypted(&index_file_path(name)).await?;
+
+            IndexFile::from_bytes(&contents).map_err(Error::IndexFile)
+        })
+    }
+
+    pub fn read_crate_file<'a>(
+        &'a self,
+        name: &'a CrateName,
+        version: &'a semver::Version,
+    ) -> BoxFuture<'a, Result<Body, Error>> {
+        Box::pin(async move {
+            let contents = self.read_encrypted(&crate_file_path(name, version)).await?;
+
+            Ok(Body::from(contents))
+        })
+    }
+
+    pub fn write_index_file<'a>(
+        &'a self,
+        name: &'a CrateName,
+        index_file: &'a IndexFile,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            if !self.encrypt_index {
+                return self.inner.write_index_file(name, index_file).await;
+            }
+
+            let contents = index_file.to_bytes().map_err(Error::IndexFile)?;
+
+            self.write_encrypted(&index_file_path(name), &contents)
+                .await
+        })
+    }
+
+    pub fn write_crate_file<'a>(
+        &'a self,
+        name: &'a CrateName,
+        version: &'a semver::Version,
+        contents: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            self.write_encrypted(&crate_file_path(name, version), contents)
+                .await
+        })
+    }
+
+    /// Other files are registry metadata, so they're encrypted along with the index
+    pub fn read_file<'a>(
+        &'a self,
+        path: &'a RelativePath,
+    ) -> BoxFuture<'a, Result<Vec<u8>, Error>> {
+        Box::pin(async move {
+            if self.encrypt_index {
+                self.read_encrypted(path).await
+            } else {
+                self.inner.read_file(path).await
+            }
+        })
+    }
+
+    pub fn write_file<'a>(
+        &'a self,
+        path: &'a RelativePath,
+        contents: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            if self.encrypt_index {
+                self.write_encrypted(path, contents).await
+            } else {
+                self.inner.write_file(path, contents).await
+            }
+        })
+    }
+
+    async fn read_encrypted(&self, path: &RelativePath) -> Result<Vec<u8>, Error> {
+        let contents = self.inner.read_file(path).await?;
+        decrypt(&self.cipher, path, &contents)
+    }
+
+    async fn write_encrypted(&self, path: &RelativePath, contents: &[u8]) -> Result<(), Error> {
+        let contents = encrypt(&self.cipher, path, contents)?;
+        self.inner.write_file(path, &contents).await
+    }
+}
+
+fn encrypt(cipher: &Aes256Gcm, path: &RelativePath, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: path.as_str().as_bytes(),
+            },
+        )
+        .map_err(|_| Error::Encryption)?;
+
+    let mut contents = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+    contents.extend_from_slice(&nonce);
+    contents.extend_from_slice(&ciphertext);
+
+    Ok(contents)
+}
+
+fn decrypt(cipher: &Aes256Gcm, path: &RelativePath, contents: &[u8]) -> Result<Vec<u8>, Error> {
+    if contents.len() < NONCE_SIZE {
+        return Err(Error::Encryption);
+    }
+
+    let (nonce, ciphertext) = contents.split_at(NONCE_SIZE);
+
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: path.as_str().as_bytes(),
+            },
+        )
+        .map_err(|_| Error::Encryption)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[7; 32]))
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let path = RelativePath::new("crates/foo/1.0.0/foo.crate");
+        let contents = encrypt(&cipher(), path, b"crate contents").unwrap();
+
+        assert_ne!(&contents[NONCE_SIZE..], b"crate contents");
+        assert_eq!(
+            decrypt(&cipher(), path, &contents).unwrap(),
+            b"crate contents"
+        );
+    }
+
+    #[test]
+    fn test_wrong_path_fails() {
+        let contents = encrypt(
+            &cipher(),
+            RelativePath::new("crates/foo/1.0.0/foo.crate"),
+            b"crate contents",
+        )
+        .unwrap();
+
+        assert!(decrypt(
+            &cipher(),
+            RelativePath::new("crates/bar/1.0.0/bar.crate"),
+            &contents
+        )
+        .is_err());
+    }
+}
@@ -2,12 +2,13 @@ use std::{io, path::PathBuf};
 
 use axum::body::Body;
 use futures::TryStreamExt;
+use relative_path::RelativePath;
 use tokio_util::io::ReaderStream;
 use tracing::{error, info};
 
 use crate::{crate_name::CrateName, index::IndexFile};
 
-use super::Error;
+use super::{crate_file_path, index_file_path, Error};
 
 pub struct LocalStorage {
     path: PathBuf,
@@ -35,8 +36,7 @@ impl LocalStorage {
     }
 
     pub async fn read_index_file(&self, crate_name: &CrateName) -> Result<IndexFile, Error> {
-        let file_path = crate_name.index_path().to_path(self.path.join("index"));
-        let contents = tokio::fs::read(file_path).await.map_err(map_io_error)?;
+        let contents = self.read_file(&index_file_path(crate_name)).await?;
 
         IndexFile::from_bytes(&contents).map_err(Error::IndexFile)
     }
@@ -46,9 +46,7 @@ impl LocalStorage {
         crate_name: &CrateName,
         version: &semver::Version,
     ) -> Result<Body, Error> {
-        let file_path = crate_name
-            .crate_path(version)
-            .to_path(self.path.join("crates"));
+        let file_path = crate_file_path(crate_name, version).to_path(&self.path);
 
         let file = tokio::fs::File::open(file_path)
             .await
@@ -64,19 +62,10 @@ impl LocalStorage {
         crate_name: &CrateName,
         index_file: &IndexFile,
     ) -> Result<(), Error> {
-        let file_path = crate_name
-            .index_path()
-            .to_path(self.path.join("index"));
         let contents = index_file.to_bytes().map_err(Error::IndexFile)?;
 
-        tokio::fs::create_dir_all(file_path.parent().unwrap())
-            .await
-            .map_err(Error::Io)?;
-        tokio::fs::write(file_path, contents)
+        self.write_file(&index_file_path(crate_name), &contents)
             .await
-            .map_err(Error::Io)?;
-
-        Ok(())
     }
 
     pub async fn write_crate_file(
@@ -85,9 +74,18 @@ impl LocalStorage {
         version: &semver::Version,
         contents: &[u8],
     ) -> Result<(), Error> {
-        let file_path = crate_name
-            .crate_path(version)
-            .to_path(self.path.join("crates"));
+        self.write_file(&crate_file_path(crate_name, version), contents)
+            .await
+    }
+
+    pub async fn read_file(&self, path: &RelativePath) -> Result<Vec<u8>, Error> {
+        tokio::fs::read(path.to_path(&self.path))
+            .await
+            .map_err(map_io_error)
+    }
+
+    pub async fn write_file(&self, path: &RelativePath, contents: &[u8]) -> Result<(), Error> {
+        let file_path = path.to_path(&self.path);
 
         tokio::fs::create_dir_all(file_path.parent().unwrap())
             .await
@@ -1,10 +1,14 @@
 use std::{borrow::Cow, env};
 
 use axum::body::Body;
-use relative_path::RelativePathBuf;
+use relative_path::RelativePath;
 use tracing::info;
 
-use crate::{crate_name::CrateName, index::IndexFile, storage::Error};
+use crate::{
+    crate_name::CrateName,
+    index::IndexFile,
+    storage::{crate_file_path, index_file_path, Error},
+};
 
 pub struct S3Storage {
     bucket: s3::Bucket,
@@ -25,21 +29,9 @@ impl S3Storage {
     }
 
     pub async fn read_index_file(&self, name: &CrateName) -> Result<IndexFile, Error> {
-        let file_path = RelativePathBuf::from("index").join(name.index_path());
+        let contents = self.read_file(&index_file_path(name)).await?;
 
-        let contents = self
-            .bucket
-            .get_object(file_path.as_str())
-            .await
-            .map_err(|e| {
-                if matches!(e, s3::error::S3Error::Http(404, _)) {
-                    Error::NotFound
-                } else {
-                    Error::S3(e)
-                }
-            })?;
-
-        IndexFile::from_bytes(contents.as_slice()).map_err(Error::IndexFile)
+        IndexFile::from_bytes(&contents).map_err(Error::IndexFile)
     }
 
     pub async fn read_crate_file(
@@ -47,7 +39,7 @@ impl S3Storage {
         name: &CrateName,
         version: &semver::Version,
     ) -> Result<Body, Error> {
-        let file_path = RelativePathBuf::from("crates").join(name.crate_path(version));
+        let file_path = crate_file_path(name, version);
 
         // TODO: rust-s3 has a get_object_stream method, but its return type is !Send, so we can't convert it to a Body.
         // So we just have to download the whole file and then serve it all at once rather than streaming it.
@@ -66,15 +58,9 @@ impl S3Storage {
         name: &CrateName,
         index_file: &IndexFile,
     ) -> Result<(), Error> {
-        let file_path = RelativePathBuf::from("index").join(name.index_path());
         let contents = index_file.to_bytes().map_err(Error::IndexFile)?;
 
-        self.bucket
-            .put_object(file_path.as_str(), &contents)
-            .await
-            .map_err(Error::S3)?;
-
-        Ok(())
+        self.write_file(&index_file_path(name), &contents).await
     }
 
     pub async fn write_crate_file(
@@ -83,10 +69,25 @@ impl S3Storage {
         version: &semver::Version,
         contents: &[u8],
     ) -> Result<(), Error> {
-        let file_path = RelativePathBuf::from("crates").join(name.crate_path(version));
+        self.write_file(&crate_file_path(name, version), contents)
+            .await
+    }
+
+    pub async fn read_file(&self, path: &RelativePath) -> Result<Vec<u8>, Error> {
+        let contents = self.bucket.get_object(path.as_str()).await.map_err(|e| {
+            if matches!(e, s3::error::S3Error::Http(404, _)) {
+                Error::NotFound
+            } else {
+                Error::S3(e)
+            }
+        })?;
+
+        Ok(contents.to_vec())
+    }
 
+    pub async fn write_file(&self, path: &RelativePath, contents: &[u8]) -> Result<(), Error> {
         self.bucket
-            .put_object(file_path.as_str(), contents)
+            .put_object(path.as_str(), contents)
             .await
             .map_err(Error::S3)?;
 